@@ -0,0 +1,46 @@
+use core::time::Duration;
+
+/// A source of wall-clock time, abstracted so id generation can run with or
+/// without the standard library.
+pub trait Clock {
+    /// Duration elapsed since the Unix epoch.
+    fn now(&self) -> Duration;
+}
+
+/// A source of random bytes, abstracted so id generation can run with or
+/// without the standard library.
+pub trait Entropy {
+    /// Fill `buf` with random bytes.
+    fn fill(&self, buf: &mut [u8]);
+}
+
+#[cfg(feature = "std")]
+mod std_impl {
+    use super::{Clock, Entropy};
+    use core::time::Duration;
+    use rand::{thread_rng, Rng};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// `Clock` backed by `std::time::SystemTime`.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct StdClock;
+
+    impl Clock for StdClock {
+        fn now(&self) -> Duration {
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+        }
+    }
+
+    /// `Entropy` backed by `rand::thread_rng`.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct StdEntropy;
+
+    impl Entropy for StdEntropy {
+        fn fill(&self, buf: &mut [u8]) {
+            thread_rng().fill_bytes(buf);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use self::std_impl::{StdClock, StdEntropy};
@@ -1,7 +1,15 @@
-use std::fmt;
-use std::time::{self, Duration, SystemTime, UNIX_EPOCH};
-use byteorder::{BigEndian, WriteBytesExt};
-use rand::{thread_rng, Rng};
+use core::fmt;
+use core::str::FromStr;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::{self, SystemTime, UNIX_EPOCH};
+use byteorder::{BigEndian, ByteOrder};
+
+use super::Entropy;
+#[cfg(feature = "std")]
+use super::StdEntropy;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 /// K-Sortable Unique ID.
 ///    - 00-03: unsigned int32 BE UTC timestamp with custom epoch
@@ -19,8 +27,63 @@ const SIZEOF_RAND: usize = 16;
 static ID_MIN_STR: &str = "000000000000000000000000000";
 static ID_MAX_STR: &str = "aWgEPTl1tmebfsQzFP4bxwgy80V";
 
+static ENCODING: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+// Plain `static` table rather than `lazy_static!`: the latter relies on
+// `std::sync::Once` to init safely, which isn't available without
+// `spin_no_std` on true `no_std` targets (no `std` to link at all, unlike
+// `--no-default-features` on a hosted target, where it's merely unused).
+// This table is fixed at compile time, so a plain `static` needs no init
+// guard.
+#[rustfmt::skip]
+static DECODING: [u8; 256] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18,
+    0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E, 0x1F, 0x20, 0x21, 0x22, 0x23, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2A, 0x2B, 0x2C, 0x2D, 0x2E, 0x2F, 0x30, 0x31, 0x32,
+    0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+/// Error returned when parsing a `Ksuid` from a string fails.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParseError {
+    /// The input was not exactly `SIZEOF_STR` bytes long.
+    InvalidLength,
+    /// A byte at `index` is not a valid base62 digit.
+    InvalidChar { index: usize, byte: u8 },
+    /// The decoded value is larger than `ID_MAX_STR`.
+    Overflow,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::InvalidLength => write!(f, "invalid ksuid length"),
+            ParseError::InvalidChar { index, byte } => {
+                write!(f, "invalid ksuid char {:?} at index {}", byte as char, index)
+            }
+            ParseError::Overflow => write!(f, "ksuid string overflows {} bytes", SIZEOF_RAW),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
 // const DEFAULT_CUSTOM_EPOCH: u64 = 1400000000;
 
+#[cfg(feature = "std")]
 lazy_static! {
     static ref DEFAULT_CUSTOM_EPOCH: SystemTime = {
         let elapsed = Duration::from_secs(1_400_000_000);
@@ -34,12 +97,104 @@ impl fmt::Debug for Ksuid {
     }
 }
 
+impl fmt::Display for Ksuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use core::str::from_utf8_unchecked;
+        let mut buf = [0; SIZEOF_STR];
+        self.encode(&mut buf);
+        f.write_str(unsafe { from_utf8_unchecked(&buf) })
+    }
+}
+
+impl FromStr for Ksuid {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ksuid::decode(s.as_bytes())
+    }
+}
+
+/// Serializes to the canonical base62 string in human-readable formats
+/// (JSON, etc.), and to the raw `[u8; 20]` in binary formats (bincode,
+/// etc.), matching `serializer.is_human_readable()`.
+#[cfg(feature = "serde")]
+impl Serialize for Ksuid {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            self.bytes.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Ksuid {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            let s = <&str>::deserialize(deserializer)?;
+            s.parse().map_err(de::Error::custom)
+        } else {
+            let bytes = <[u8; SIZEOF_RAW]>::deserialize(deserializer)?;
+            Ok(Ksuid { bytes })
+        }
+    }
+}
+
+impl Ksuid {
+    pub fn encode(&self, dst: &mut [u8]) {
+        let mut digits = self.bytes;
+        for i in (0..SIZEOF_STR).rev() {
+            let mut remainder: u32 = 0;
+            for b in digits.iter_mut() {
+                let acc = (remainder << 8) | u32::from(*b);
+                *b = (acc / 62) as u8;
+                remainder = acc % 62;
+            }
+            dst[i] = ENCODING[remainder as usize];
+        }
+    }
+
+    pub fn decode(src: &[u8]) -> Result<Ksuid, ParseError> {
+        if src.len() != SIZEOF_STR {
+            return Err(ParseError::InvalidLength);
+        }
+
+        let mut bytes = [0u8; SIZEOF_RAW];
+        for (index, &byte) in src.iter().enumerate() {
+            let digit = DECODING[byte as usize];
+            if digit == 0xFF {
+                return Err(ParseError::InvalidChar { index, byte });
+            }
+
+            let mut carry = u32::from(digit);
+            for b in bytes.iter_mut().rev() {
+                let acc = u32::from(*b) * 62 + carry;
+                *b = acc as u8;
+                carry = acc >> 8;
+            }
+            if carry != 0 {
+                return Err(ParseError::Overflow);
+            }
+        }
+
+        Ok(Ksuid { bytes })
+    }
+}
+
 /// `CustomEpoch` represents epoch that starts more recently.
 #[derive(Debug, Clone)]
 pub struct CustomEpoch {
     // diff from UNIX_EPOCH
     diff: Duration,
     // UNIX_EPOCH + diff_from_unix_epoch
+    #[cfg(feature = "std")]
     epoch: SystemTime,
 }
 
@@ -55,12 +210,14 @@ impl Default for CustomEpoch {
     fn default() -> Self {
         CustomEpoch {
             diff: Duration::from_secs(1_400_000_000),
+            #[cfg(feature = "std")]
             epoch: *DEFAULT_CUSTOM_EPOCH,
         }
     }
 }
 
 impl CustomEpoch {
+    #[cfg(feature = "std")]
     fn new(diff: Duration) -> Self {
         CustomEpoch {
             diff,
@@ -78,15 +235,21 @@ impl CustomEpoch {
         ts + self.diff
     }
 
-    fn ksuid(&self, unixtime: Duration) -> Ksuid {
+    /// Generate a `Ksuid` for `unixtime` from an explicit entropy source.
+    /// This is the `no_std`-friendly entry point; `ksuid` is a thin wrapper
+    /// around it for `std` targets.
+    pub fn ksuid_with<E: Entropy>(&self, unixtime: Duration, entropy: &E) -> Ksuid {
         let mut bytes = [0u8; SIZEOF_RAW];
-        thread_rng().fill_bytes(&mut bytes[SIZEOF_TIME..]);
+        entropy.fill(&mut bytes[SIZEOF_TIME..]);
         let ts = self.adjust_to_custom_epoch(unixtime);
-        (&mut bytes[..SIZEOF_TIME])
-            .write_u32::<BigEndian>(ts.as_secs() as u32)
-            .expect("write timestamp");
+        BigEndian::write_u32(&mut bytes[..SIZEOF_TIME], ts.as_secs() as u32);
         Ksuid { bytes }
     }
+
+    #[cfg(feature = "std")]
+    fn ksuid(&self, unixtime: Duration) -> Ksuid {
+        self.ksuid_with(unixtime, &StdEntropy)
+    }
 }
 
 #[cfg(test)]
@@ -101,4 +264,77 @@ mod tests {
             println!("{:?}", epoch.ksuid(::now()));
         }
     }
+
+    #[test]
+    fn test_encode_decode() {
+        let epoch = CustomEpoch::default();
+
+        for _ in 0..10 {
+            let id1 = epoch.ksuid(::now());
+            let id2: Ksuid = id1.to_string().parse().unwrap();
+            assert_eq!(id1, id2);
+            assert_eq!(id1.to_string(), id2.to_string());
+        }
+    }
+
+    #[test]
+    fn test_string_length() {
+        let id = CustomEpoch::default().ksuid(::now());
+        assert_eq!(SIZEOF_STR, id.to_string().len());
+    }
+
+    #[test]
+    fn test_min_max() {
+        assert_eq!(
+            Ksuid::default(),
+            ID_MIN_STR.parse().unwrap()
+        );
+        assert_eq!(
+            Ksuid {
+                bytes: [0xFF; SIZEOF_RAW],
+            },
+            ID_MAX_STR.parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_length() {
+        assert_eq!(Err(ParseError::InvalidLength), Ksuid::decode(b"too-short"));
+    }
+
+    #[test]
+    fn test_invalid_char() {
+        let mut buf = [b'0'; SIZEOF_STR];
+        buf[0] = b'?';
+        assert_eq!(
+            Err(ParseError::InvalidChar { index: 0, byte: b'?' }),
+            Ksuid::decode(&buf)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_round_trip() {
+        let id1 = CustomEpoch::default().ksuid(::now());
+        let json = serde_json::to_string(&id1).unwrap();
+        assert_eq!(format!("{:?}", id1.to_string()), json);
+        let id2: Ksuid = serde_json::from_str(&json).unwrap();
+        assert_eq!(id1, id2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_invalid() {
+        let err = serde_json::from_str::<Ksuid>("\"too-short\"");
+        assert!(err.is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_bincode_round_trip() {
+        let id1 = CustomEpoch::default().ksuid(::now());
+        let bytes = bincode::serialize(&id1).unwrap();
+        let id2: Ksuid = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(id1, id2);
+    }
 }
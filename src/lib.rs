@@ -1,20 +1,50 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![feature(getpid, integer_atomics)]
 
+// Edition 2015 treats a leading `::` as "crate root" (used by the `::now()`
+// call sites in `ksuid.rs`/`ulid.rs`), so the `core::` paths in `clock.rs`
+// and `xid.rs` need an explicit `extern crate core;` to resolve `core` as a
+// root-level name. `#[no_std]` builds already declare `core` implicitly, so
+// this is only needed for the default `std`-enabled build.
+#[cfg(feature = "std")]
+extern crate core;
+
 extern crate byteorder;
+#[cfg(feature = "std")]
 extern crate crc;
+#[cfg(feature = "std")]
 extern crate crypto;
+#[cfg(feature = "std")]
 extern crate hostname;
 #[macro_use]
 extern crate lazy_static;
 extern crate rand;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate bincode;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+mod clock;
+
+pub use clock::{Clock, Entropy};
+#[cfg(feature = "std")]
+pub use clock::{StdClock, StdEntropy};
 
 pub mod xid;
-// pub mod ksuid;
-// pub mod ulid;
+pub mod ksuid;
+// `Ulid` generation is built directly on `std::time::SystemTime` and
+// `std::sync::Mutex`; unlike `xid`/`ksuid` it hasn't been migrated onto the
+// `Clock`/`Entropy` traits yet, so it stays `std`-only for now.
+#[cfg(feature = "std")]
+pub mod ulid;
 
+#[cfg(feature = "std")]
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Return duration from epoch.
+/// Return duration from epoch, using the standard library clock.
+#[cfg(feature = "std")]
 fn now() -> Duration {
     SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
 }
@@ -0,0 +1,278 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rand::{thread_rng, Rng};
+
+/// Universally Unique Lexicographically Sortable Identifier.
+///    - 00-05: 48-bit big-endian millisecond Unix timestamp
+///    - 06-15: 80 bits of randomness
+///
+/// This module is only available with the `std` feature: generation is
+/// built directly on `SystemTime`/`Mutex` rather than the `Clock`/`Entropy`
+/// traits `xid` and `ksuid` use, so it has no `no_std` story yet.
+#[derive(Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Ulid {
+    bytes: [u8; SIZEOF_RAW],
+}
+
+const SIZEOF_RAW: usize = 16;
+const SIZEOF_STR: usize = 26;
+const SIZEOF_TIME: usize = 6;
+const SIZEOF_RAND: usize = 10;
+
+const MAX_RANDOM: u128 = (1u128 << 80) - 1;
+
+lazy_static! {
+    static ref ENCODING: [u8; 32] = {
+        let mut buf = [0; 32];
+        let text = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+        (&mut buf[..]).clone_from_slice(text);
+        buf
+    };
+
+    static ref DECODING: [u8; 256] = {
+        let mut buf = [0; 256];
+        for p in &mut buf[..] {
+            *p = 0xFF;
+        }
+        for i in 0..ENCODING.len() {
+            buf[ENCODING[i] as usize] = i as u8;
+        }
+        buf
+    };
+}
+
+struct Monotonic {
+    millis: u64,
+    random: u128,
+}
+
+lazy_static! {
+    static ref MONOTONIC: Mutex<Monotonic> = Mutex::new(Monotonic {
+        millis: 0,
+        random: 0,
+    });
+}
+
+/// Error returned when parsing a `Ulid` from a string or byte slice fails.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParseError {
+    /// The input was not exactly `SIZEOF_STR` bytes long.
+    InvalidLength,
+    /// A byte at `index` does not map to a Crockford base32 symbol.
+    InvalidChar { index: usize, byte: u8 },
+    /// The input decodes to a value wider than 128 bits.
+    Overflow,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::InvalidLength => write!(f, "invalid ulid length"),
+            ParseError::InvalidChar { index, byte } => {
+                write!(f, "invalid ulid char {:?} at index {}", byte as char, index)
+            }
+            ParseError::Overflow => write!(f, "ulid string overflows 128 bits"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Default for Ulid {
+    fn default() -> Self {
+        Ulid {
+            bytes: [0; SIZEOF_RAW],
+        }
+    }
+}
+
+impl fmt::Debug for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.bytes.fmt(f)
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::str::from_utf8_unchecked;
+        let mut buf = [0; SIZEOF_STR];
+        self.encode(&mut buf);
+        f.write_str(unsafe { from_utf8_unchecked(&buf) })
+    }
+}
+
+impl FromStr for Ulid {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ulid::decode(s.as_bytes())
+    }
+}
+
+fn random_80() -> u128 {
+    let mut buf = [0u8; SIZEOF_RAND];
+    thread_rng().fill_bytes(&mut buf);
+    let mut value = 0u128;
+    for &byte in &buf {
+        value = (value << 8) | u128::from(byte);
+    }
+    value
+}
+
+fn now_millis() -> u64 {
+    let elapsed = ::now();
+    elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())
+}
+
+impl Ulid {
+    /// Generate a new, monotonically increasing `Ulid`.
+    ///
+    /// Within the same millisecond, the 80-bit random field is incremented
+    /// by one instead of being redrawn; if that increment overflows, the
+    /// timestamp is bumped by a millisecond and the random field reset.
+    pub fn new() -> Ulid {
+        let millis = now_millis();
+        let mut state = MONOTONIC.lock().unwrap();
+
+        if millis > state.millis {
+            state.millis = millis;
+            state.random = random_80();
+        } else {
+            state.random += 1;
+            if state.random > MAX_RANDOM {
+                state.millis += 1;
+                state.random = 0;
+            }
+        }
+
+        Ulid::from_parts(state.millis, state.random)
+    }
+
+    fn from_parts(millis: u64, random: u128) -> Ulid {
+        let mut bytes = [0; SIZEOF_RAW];
+
+        for i in 0..SIZEOF_TIME {
+            bytes[i] = (millis >> ((SIZEOF_TIME - 1 - i) * 8)) as u8;
+        }
+        for i in 0..SIZEOF_RAND {
+            bytes[SIZEOF_TIME + i] = (random >> ((SIZEOF_RAND - 1 - i) * 8)) as u8;
+        }
+
+        Ulid { bytes }
+    }
+
+    fn as_u128(&self) -> u128 {
+        let mut value = 0u128;
+        for &byte in &self.bytes {
+            value = (value << 8) | u128::from(byte);
+        }
+        value
+    }
+
+    /// The embedded millisecond Unix timestamp.
+    pub fn timestamp(&self) -> SystemTime {
+        let mut millis = 0u64;
+        for i in 0..SIZEOF_TIME {
+            millis = (millis << 8) | u64::from(self.bytes[i]);
+        }
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    /// The embedded 80 bits of randomness.
+    pub fn random(&self) -> u128 {
+        let mut value = 0u128;
+        for i in 0..SIZEOF_RAND {
+            value = (value << 8) | u128::from(self.bytes[SIZEOF_TIME + i]);
+        }
+        value
+    }
+
+    pub fn encode(&self, dst: &mut [u8]) {
+        let mut value = self.as_u128();
+        for i in (0..SIZEOF_STR).rev() {
+            dst[i] = ENCODING[(value & 0x1F) as usize];
+            value >>= 5;
+        }
+    }
+
+    pub fn decode(src: &[u8]) -> Result<Ulid, ParseError> {
+        if src.len() != SIZEOF_STR {
+            return Err(ParseError::InvalidLength);
+        }
+
+        let mut value: u128 = 0;
+        for (index, &byte) in src.iter().enumerate() {
+            let digit = DECODING[byte as usize];
+            if digit == 0xFF {
+                return Err(ParseError::InvalidChar { index, byte });
+            }
+            if index == 0 && digit > 7 {
+                return Err(ParseError::Overflow);
+            }
+            value = (value << 5) | u128::from(digit);
+        }
+
+        let mut bytes = [0u8; SIZEOF_RAW];
+        for i in 0..SIZEOF_RAW {
+            bytes[SIZEOF_RAW - 1 - i] = (value >> (i * 8)) as u8;
+        }
+        Ok(Ulid { bytes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ord() {
+        let id1 = Ulid::new();
+        let id2 = Ulid::new();
+        let id3 = Ulid::new();
+        assert!(id1 <= id2);
+        assert!(id2 <= id3);
+        assert!(id1.to_string() <= id2.to_string());
+        assert!(id2.to_string() <= id3.to_string());
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        for _ in 0..10 {
+            let id1 = Ulid::new();
+            let id2: Ulid = id1.to_string().parse().unwrap();
+            assert_eq!(id1, id2);
+            assert_eq!(id1.to_string(), id2.to_string());
+        }
+    }
+
+    #[test]
+    fn test_string_length() {
+        let id = Ulid::new();
+        assert_eq!(SIZEOF_STR, id.to_string().len());
+    }
+
+    #[test]
+    fn test_invalid_length() {
+        assert_eq!(Err(ParseError::InvalidLength), Ulid::decode(b"too-short"));
+    }
+
+    #[test]
+    fn test_invalid_char() {
+        let mut buf = [0u8; SIZEOF_STR];
+        Ulid::new().encode(&mut buf);
+        buf[0] = b'?';
+        assert_eq!(
+            Err(ParseError::InvalidChar { index: 0, byte: b'?' }),
+            Ulid::decode(&buf)
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut buf = [b'0'; SIZEOF_STR];
+        buf[0] = b'8';
+        assert_eq!(Err(ParseError::Overflow), Ulid::decode(&buf));
+    }
+}
@@ -1,12 +1,29 @@
-use std::{fmt, fs, io, process};
-use std::sync::atomic::{AtomicU32, Ordering};
-use byteorder::{BigEndian, WriteBytesExt};
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::{fs, io, process};
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+use byteorder::{BigEndian, ByteOrder};
+#[cfg(feature = "std")]
 use crc::crc32;
+#[cfg(feature = "std")]
 use crypto::digest::Digest;
+#[cfg(feature = "std")]
 use crypto::md5;
+#[cfg(feature = "std")]
 use hostname;
+#[cfg(feature = "std")]
 use rand::{thread_rng, Rng};
 
+use super::{Clock, Entropy};
+#[cfg(feature = "std")]
+use super::{StdClock, StdEntropy};
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
 #[derive(Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Xid {
     bytes: [u8; SIZEOF_RAW],
@@ -15,27 +32,38 @@ pub struct Xid {
 const SIZEOF_RAW: usize = 12;
 const SIZEOF_STR: usize = 20;
 
+// Plain `static` tables rather than `lazy_static!`: the latter relies on
+// `std::sync::Once` to init safely, which isn't available without
+// `spin_no_std` on true `no_std` targets (no `std` to link at all, unlike
+// `--no-default-features` on a hosted target, where it's merely unused).
+// These tables are fixed at compile time, so a plain `static` needs no init
+// guard.
+static ENCODING: [u8; 32] = *b"0123456789abcdefghijklmnopqrstuv";
+
+#[rustfmt::skip]
+static DECODING: [u8; 256] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18,
+    0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E, 0x1F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+#[cfg(feature = "std")]
 lazy_static! {
-    static ref ENCODING: [u8; 32] = {
-        let mut buf = [0; 32];
-        let text = b"0123456789abcdefghijklmnopqrstuv";
-        (&mut buf[..]).clone_from_slice(text);
-        buf
-    };
-
-    static ref DECODING: [u8; 256] = {
-        let mut buf = [0; 256];
-        for p in &mut buf[..] {
-            *p = 0xFF;
-        }
-        for i in 0..ENCODING.len() {
-            buf[ENCODING[i] as usize] = i as u8;
-        }
-        buf
-    };
-}
-
-lazy_static! {
+    /// Process id, derived like upstream `xid`: the real pid, unless we're
+    /// pid 1 in a container, in which case a checksum of our cgroup.
     static ref PROCESS: u32 = {
         let getpid = || {
             let pid = process::id();
@@ -53,6 +81,8 @@ lazy_static! {
         getpid()
     };
 
+    /// Machine id, derived from the hostname (falling back to random bytes
+    /// if the hostname can't be read).
     static ref MACHINE: [u8; 3] = {
         let getmid = || {
             if let Some(host) = hostname::get_hostname() {
@@ -73,12 +103,68 @@ lazy_static! {
         id[..3].clone_from_slice(&getmid()[..3]);
         id
     };
+}
 
-    static ref COUNTER: AtomicU32 = {
-        let mut buf = [0u8; 3];
-        thread_rng().fill_bytes(&mut buf);
-        AtomicU32::new((u32::from(buf[0]) << 16) | (u32::from(buf[1]) << 8) | u32::from(buf[2]))
-    };
+/// Number of bits the per-second object counter occupies in `STATE`.
+const COUNTER_BITS: u32 = 24;
+const COUNTER_MASK: u64 = (1 << COUNTER_BITS) - 1;
+
+/// Packs the last-used `(timestamp, counter)` pair so it can be updated
+/// atomically with a single compare-and-swap, instead of racing a separate
+/// clock read against a separate counter increment.
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Advance the monotonic `(timestamp, counter)` state past `now` and return
+/// the pair to use for the next id. Lock-free: on contention, just retries
+/// with the freshly observed state, like a CAS-based pool allocator.
+///
+/// `STATE == 0` is used as a one-time "never generated an id yet in this
+/// process" sentinel (no real-world timestamp is 0): the first call draws a
+/// random starting counter from `entropy` instead of starting at zero, so
+/// that two processes sharing a machine id, process id and wall-clock
+/// second across a fast restart don't emit identical ids.
+fn advance<E: Entropy>(now: u32, entropy: &E) -> (u32, u32) {
+    loop {
+        let packed = STATE.load(Ordering::SeqCst);
+
+        if packed == 0 {
+            let mut buf = [0u8; 3];
+            entropy.fill(&mut buf);
+            let seed_counter =
+                (u32::from(buf[0]) << 16) | (u32::from(buf[1]) << 8) | u32::from(buf[2]);
+            let seeded = (u64::from(now) << COUNTER_BITS) | u64::from(seed_counter);
+            if STATE
+                .compare_exchange_weak(0, seeded, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return (now, seed_counter);
+            }
+            continue;
+        }
+
+        let last_ts = (packed >> COUNTER_BITS) as u32;
+        let last_counter = (packed & COUNTER_MASK) as u32;
+
+        let candidate_ts = if now > last_ts { now } else { last_ts };
+        let (next_ts, next_counter) = if candidate_ts == last_ts {
+            let counter = last_counter + 1;
+            if counter > COUNTER_MASK as u32 {
+                (candidate_ts + 1, 0)
+            } else {
+                (candidate_ts, counter)
+            }
+        } else {
+            (candidate_ts, 0)
+        };
+
+        let next_packed = (u64::from(next_ts) << COUNTER_BITS) | u64::from(next_counter);
+        if STATE
+            .compare_exchange_weak(packed, next_packed, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            return (next_ts, next_counter);
+        }
+    }
 }
 
 impl Default for Xid {
@@ -95,35 +181,138 @@ impl fmt::Debug for Xid {
 }
 impl fmt::Display for Xid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use std::str::from_utf8_unchecked;
+        use core::str::from_utf8_unchecked;
         let mut buf = [0; SIZEOF_STR];
         self.encode(&mut buf);
         f.write_str(unsafe { from_utf8_unchecked(&buf) })
     }
 }
 
-impl<T> From<T> for Xid
-where
-    T: AsRef<[u8]>,
-{
-    fn from(data: T) -> Self {
-        let mut bytes = [0; SIZEOF_RAW];
-        bytes[..SIZEOF_RAW].clone_from_slice(&data.as_ref()[..SIZEOF_RAW]);
-        Xid { bytes }
+/// Error returned when parsing an `Xid` from its encoded string form fails.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParseError {
+    /// The input was not exactly `SIZEOF_STR` bytes long.
+    InvalidLength,
+    /// A byte at `index` does not map to a base32 symbol.
+    InvalidChar { index: usize, byte: u8 },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::InvalidLength => write!(f, "invalid xid length"),
+            ParseError::InvalidChar { index, byte } => {
+                write!(f, "invalid xid char {:?} at index {}", byte as char, index)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+impl<'a> TryFrom<&'a [u8]> for Xid {
+    type Error = ParseError;
+
+    fn try_from(src: &'a [u8]) -> Result<Self, Self::Error> {
+        if src.len() != SIZEOF_STR {
+            return Err(ParseError::InvalidLength);
+        }
+        for (index, &byte) in src.iter().enumerate() {
+            if DECODING[byte as usize] == 0xFF {
+                return Err(ParseError::InvalidChar { index, byte });
+            }
+        }
+
+        let mut xid = Xid::default();
+        xid.decode(src);
+        Ok(xid)
+    }
+}
+
+impl FromStr for Xid {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Xid::try_from(s.as_bytes())
+    }
+}
+
+/// Serializes to the canonical base32 string in human-readable formats
+/// (JSON, etc.), and to the raw `[u8; 12]` in binary formats (bincode,
+/// etc.), matching `serializer.is_human_readable()`.
+#[cfg(feature = "serde")]
+impl Serialize for Xid {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            self.bytes.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Xid {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            let s = <&str>::deserialize(deserializer)?;
+            s.parse().map_err(de::Error::custom)
+        } else {
+            let bytes = <[u8; SIZEOF_RAW]>::deserialize(deserializer)?;
+            Ok(Xid { bytes })
+        }
     }
 }
 
 impl Xid {
+    /// Generate a new `Xid` from an explicit clock, entropy source, machine
+    /// id and process id. This is the `no_std`-friendly entry point; `new`
+    /// is a thin wrapper around it for `std` targets.
+    ///
+    /// Ids are strictly increasing and collision-free even when generated
+    /// concurrently, or across a backwards clock step: the timestamp and
+    /// counter are advanced together under a single compare-and-swap (see
+    /// `advance`), so neither can move without the other. The counter's
+    /// starting value is also drawn from `entropy` the first time this
+    /// process generates an id, so that two processes sharing a machine id
+    /// and process id across a fast restart don't start from the same
+    /// `(timestamp, 0)` pair.
+    pub fn generate<C: Clock, E: Entropy>(
+        clock: &C,
+        entropy: &E,
+        machine: [u8; 3],
+        process: u32,
+    ) -> Xid {
+        let now = clock.now().as_secs() as u32;
+        let (ts, counter) = advance(now, entropy);
+        Xid::from_parts(ts, machine, process, counter)
+    }
+
+    #[cfg(feature = "std")]
     pub fn new() -> Xid {
-        let now = ::now().as_secs() as u32;
-        let old = COUNTER.fetch_add(1, Ordering::SeqCst);
-        Xid::from_parts(now, *MACHINE, *PROCESS, old + 1)
+        Xid::generate(&StdClock, &StdEntropy, *MACHINE, *PROCESS)
+    }
+
+    /// Build an `Xid` directly from its `SIZEOF_RAW`-byte raw representation,
+    /// trusting the caller (unlike `FromStr`/`TryFrom<&[u8]>`, which parse
+    /// and validate the encoded string form).
+    pub fn from_raw<T: AsRef<[u8]>>(data: T) -> Xid {
+        let mut bytes = [0; SIZEOF_RAW];
+        bytes[..SIZEOF_RAW].clone_from_slice(&data.as_ref()[..SIZEOF_RAW]);
+        Xid { bytes }
     }
 
     fn from_parts(ts: u32, mid: [u8; 3], pid: u32, obj: u32) -> Xid {
         let mut bytes = [0; SIZEOF_RAW];
 
-        (&mut bytes[..4]).write_u32::<BigEndian>(ts).unwrap();
+        BigEndian::write_u32(&mut bytes[..4], ts);
 
         bytes[4] = mid[0];
         bytes[5] = mid[1];
@@ -194,27 +383,31 @@ impl Xid {
         bytes[10] = (dec!(16) << 3) | (dec!(17) >> 2);
         bytes[11] = (dec!(17) << 6) | (dec!(18) << 1) | (dec!(19) >> 4);
     }
-}
 
-#[cfg(test)]
-impl Xid {
-    fn timestamp(&self) -> u32 {
-        use byteorder::ReadBytesExt;
-        let mut r = io::Cursor::new(&self.bytes[0..4]);
-        r.read_u32::<BigEndian>().unwrap()
+    /// The embedded Unix timestamp, in seconds.
+    pub fn timestamp(&self) -> u32 {
+        BigEndian::read_u32(&self.bytes[0..4])
     }
 
-    fn machine(&self) -> &[u8] {
+    /// The embedded Unix timestamp, as a `SystemTime`.
+    #[cfg(feature = "std")]
+    pub fn timestamp_system(&self) -> SystemTime {
+        use std::time::{Duration, UNIX_EPOCH};
+        UNIX_EPOCH + Duration::from_secs(u64::from(self.timestamp()))
+    }
+
+    /// The embedded 3-byte machine id.
+    pub fn machine(&self) -> &[u8] {
         &self.bytes[4..7]
     }
 
-    fn process(&self) -> u32 {
-        use byteorder::ReadBytesExt;
-        let mut r = io::Cursor::new(&self.bytes[7..9]);
-        r.read_u16::<BigEndian>().map(u32::from).unwrap()
+    /// The embedded process id.
+    pub fn process(&self) -> u32 {
+        u32::from(BigEndian::read_u16(&self.bytes[7..9]))
     }
 
-    fn counter(&self) -> u32 {
+    /// The embedded per-second object counter.
+    pub fn counter(&self) -> u32 {
         let buf = &self.bytes[9..12];
         (u32::from(buf[0]) << 16) | (u32::from(buf[1]) << 8) | u32::from(buf[2])
     }
@@ -269,6 +462,53 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_round_trip() {
+        let id1 = Xid::new();
+        let id2: Xid = id1.to_string().parse().unwrap();
+        assert_eq!(id1, id2);
+    }
+
+    #[test]
+    fn test_parse_invalid_length() {
+        assert_eq!(Err(ParseError::InvalidLength), "too-short".parse::<Xid>());
+    }
+
+    #[test]
+    fn test_parse_invalid_char() {
+        let mut buf = [0u8; SIZEOF_STR];
+        Xid::new().encode(&mut buf);
+        buf[0] = b'?';
+        let s = String::from_utf8(buf.to_vec()).unwrap();
+        assert_eq!(
+            Err(ParseError::InvalidChar { index: 0, byte: b'?' }),
+            s.parse::<Xid>()
+        );
+    }
+
+    #[test]
+    fn test_concurrent_generation_is_unique_and_sorted() {
+        use std::collections::HashSet;
+        use std::thread;
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| thread::spawn(|| (0..1000).map(|_| Xid::new()).collect::<Vec<_>>()))
+            .collect();
+
+        let per_thread: Vec<Vec<Xid>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        // Each thread's own ids are generated one after another, so they
+        // must come out in strictly increasing order.
+        for ids in &per_thread {
+            assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        }
+
+        // No two ids, even across threads, should ever collide.
+        let all: Vec<Xid> = per_thread.into_iter().flatten().collect();
+        let unique: HashSet<_> = all.iter().cloned().collect();
+        assert_eq!(all.len(), unique.len());
+    }
+
     #[test]
     fn test_from_parts() {
         let tests = &[
@@ -277,7 +517,7 @@ mod tests {
                 machine: [0x60, 0xf4, 0x86],
                 process: 0xe428,
                 counter: 4271561,
-                expect_id: Xid::from([
+                expect_id: Xid::from_raw([
                     0x4d, 0x88, 0xe1, 0x5b, 0x60, 0xf4, 0x86, 0xe4, 0x28, 0x41, 0x2d, 0xc9
                 ]),
             },
@@ -286,7 +526,7 @@ mod tests {
                 machine: [0x00, 0x00, 0x00],
                 process: 0x0000,
                 counter: 0,
-                expect_id: Xid::from([
+                expect_id: Xid::from_raw([
                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
                 ]),
             },
@@ -295,7 +535,7 @@ mod tests {
                 machine: [0xaa, 0xbb, 0xcc],
                 process: 0xddee,
                 counter: 1,
-                expect_id: Xid::from([
+                expect_id: Xid::from_raw([
                     0x00, 0x00, 0x00, 0x00, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0x00, 0x00, 0x01
                 ]),
             },
@@ -305,4 +545,30 @@ mod tests {
             test.run();
         }
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_round_trip() {
+        let id1 = Xid::new();
+        let json = serde_json::to_string(&id1).unwrap();
+        assert_eq!(format!("{:?}", id1.to_string()), json);
+        let id2: Xid = serde_json::from_str(&json).unwrap();
+        assert_eq!(id1, id2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_invalid() {
+        let err = serde_json::from_str::<Xid>("\"too-short\"");
+        assert!(err.is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_bincode_round_trip() {
+        let id1 = Xid::new();
+        let bytes = bincode::serialize(&id1).unwrap();
+        let id2: Xid = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(id1, id2);
+    }
 }